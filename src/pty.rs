@@ -1,27 +1,35 @@
 use mio::unix::SourceFd;
-use nix::{fcntl, libc, pty, sys::signal, sys::wait, unistd, unistd::ForkResult};
+use nix::{fcntl, libc, pty, sys::signal, sys::signalfd, sys::wait, unistd, unistd::ForkResult};
 use std::fs;
 use std::io::{self, Read, Write};
 use std::ops::Deref;
-use std::os::fd::RawFd;
-use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::os::fd::{OwnedFd, RawFd};
+use std::os::unix::io::AsRawFd;
 use termion::raw::IntoRawMode;
 
 pub trait Recorder {
     fn start(&mut self, size: (u16, u16)) -> io::Result<()>;
     fn output(&mut self, data: &[u8]);
     fn input(&mut self, data: &[u8]);
+    fn resize(&mut self, size: (u16, u16));
 }
 
 pub fn exec<S: AsRef<str>, R: Recorder>(args: &[S], recorder: &mut R) -> anyhow::Result<i32> {
     let tty = open_tty()?;
     let winsize = get_tty_size(tty.as_raw_fd());
     recorder.start((winsize.ws_col, winsize.ws_row))?;
+
+    let mut sigwinch_mask = signal::SigSet::empty();
+    sigwinch_mask.add(signal::Signal::SIGWINCH);
+    sigwinch_mask.thread_block()?;
+    let signal_fd =
+        signalfd::SignalFd::with_flags(&sigwinch_mask, signalfd::SfdFlags::SFD_NONBLOCK)?;
+
     let result = unsafe { pty::forkpty(Some(&winsize), None) }?;
 
     match result.fork_result {
         ForkResult::Parent { child } => {
-            handle_parent(result.master.as_raw_fd(), tty, child, recorder)
+            handle_parent(result.master, tty, child, winsize, signal_fd, recorder)
         }
 
         ForkResult::Child => {
@@ -52,12 +60,14 @@ fn get_tty_size(tty_fd: i32) -> pty::Winsize {
 }
 
 fn handle_parent<R: Recorder>(
-    master_fd: RawFd,
+    master: OwnedFd,
     tty: fs::File,
     child: unistd::Pid,
+    winsize: pty::Winsize,
+    signal_fd: signalfd::SignalFd,
     recorder: &mut R,
 ) -> anyhow::Result<i32> {
-    let copy_result = copy(master_fd, tty, recorder);
+    let copy_result = copy(master, tty, winsize, signal_fd, recorder);
     let wait_result = wait::waitpid(child, None);
     copy_result?;
 
@@ -71,16 +81,26 @@ fn handle_parent<R: Recorder>(
 
 const MASTER: mio::Token = mio::Token(0);
 const TTY: mio::Token = mio::Token(1);
+const SIGNAL: mio::Token = mio::Token(2);
 const BUF_SIZE: usize = 128 * 1024;
 
-fn copy<R: Recorder>(master_fd: RawFd, tty: fs::File, recorder: &mut R) -> anyhow::Result<()> {
-    let mut master = unsafe { fs::File::from_raw_fd(master_fd) };
+fn copy<R: Recorder>(
+    master: OwnedFd,
+    tty: fs::File,
+    mut last_size: pty::Winsize,
+    signal_fd: signalfd::SignalFd,
+    recorder: &mut R,
+) -> anyhow::Result<()> {
+    let mut master = fs::File::from(master);
     let mut poll = mio::Poll::new()?;
     let mut events = mio::Events::with_capacity(128);
+    let master_fd = master.as_raw_fd();
     let mut master_source = SourceFd(&master_fd);
     let mut tty = tty.into_raw_mode()?;
     let tty_fd = tty.as_raw_fd();
     let mut tty_source = SourceFd(&tty_fd);
+    let signal_raw_fd = signal_fd.as_raw_fd();
+    let mut signal_source = SourceFd(&signal_raw_fd);
     let mut buf = [0u8; BUF_SIZE];
     let mut input: Vec<u8> = Vec::with_capacity(BUF_SIZE);
     let mut output: Vec<u8> = Vec::with_capacity(BUF_SIZE);
@@ -95,6 +115,9 @@ fn copy<R: Recorder>(master_fd: RawFd, tty: fs::File, recorder: &mut R) -> anyho
     poll.registry()
         .register(&mut tty_source, TTY, mio::Interest::READABLE)?;
 
+    poll.registry()
+        .register(&mut signal_source, SIGNAL, mio::Interest::READABLE)?;
+
     loop {
         poll.poll(&mut events, None).unwrap();
 
@@ -177,6 +200,22 @@ fn copy<R: Recorder>(master_fd: RawFd, tty: fs::File, recorder: &mut R) -> anyho
                     }
                 }
 
+                SIGNAL => {
+                    if event.is_readable() {
+                        while signal_fd.read_signal()?.is_some() {}
+
+                        let winsize = get_tty_size(tty_fd);
+
+                        if (winsize.ws_col, winsize.ws_row)
+                            != (last_size.ws_col, last_size.ws_row)
+                        {
+                            last_size = winsize;
+                            unsafe { libc::ioctl(master_fd, libc::TIOCSWINSZ, &winsize) };
+                            recorder.resize((winsize.ws_col, winsize.ws_row));
+                        }
+                    }
+                }
+
                 _ => (),
             }
         }
@@ -193,6 +232,11 @@ fn handle_child<S: AsRef<str>>(args: &[S]) -> anyhow::Result<()> {
         .collect::<Result<Vec<CString>, NulError>>()?;
 
     unsafe { signal::signal(Signal::SIGPIPE, SigHandler::SigDfl) }?;
+
+    let mut sigwinch_mask = signal::SigSet::empty();
+    sigwinch_mask.add(Signal::SIGWINCH);
+    sigwinch_mask.thread_unblock()?;
+
     unistd::execvp(&args[0], &args)?;
     unsafe { libc::_exit(1) }
 }
@@ -261,4 +305,254 @@ fn write_all<W: Write>(sink: &mut W, data: &mut Vec<u8>) -> io::Result<usize> {
     }
 
     Ok(left)
-}
\ No newline at end of file
+}
+
+#[cfg(feature = "async")]
+pub mod asynchronous {
+    use super::*;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use tokio::io::unix::AsyncFd;
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+
+    #[allow(async_fn_in_trait)]
+    pub trait AsyncRecorder {
+        async fn start(&mut self, size: (u16, u16)) -> io::Result<()>;
+        async fn output(&mut self, data: &[u8]);
+        async fn input(&mut self, data: &[u8]);
+        async fn resize(&mut self, size: (u16, u16));
+    }
+
+    struct PtyIo(RawFd);
+
+    impl AsRawFd for PtyIo {
+        fn as_raw_fd(&self) -> RawFd {
+            self.0
+        }
+    }
+
+    struct AsyncPty {
+        inner: AsyncFd<PtyIo>,
+    }
+
+    impl AsyncPty {
+        fn new(fd: RawFd) -> io::Result<Self> {
+            set_non_blocking(&fd)?;
+
+            Ok(Self {
+                inner: AsyncFd::new(PtyIo(fd))?,
+            })
+        }
+    }
+
+    impl AsyncRead for AsyncPty {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            let this = self.get_mut();
+
+            loop {
+                let mut guard = match this.inner.poll_read_ready(cx) {
+                    Poll::Ready(guard) => guard?,
+                    Poll::Pending => return Poll::Pending,
+                };
+
+                let result = guard.try_io(|inner| {
+                    let unfilled = buf.initialize_unfilled();
+                    nix::unistd::read(inner.get_ref().0, unfilled).map_err(io::Error::from)
+                });
+
+                match result {
+                    Ok(Ok(n)) => {
+                        buf.advance(n);
+                        return Poll::Ready(Ok(()));
+                    }
+                    Ok(Err(e)) => return Poll::Ready(Err(e)),
+                    Err(_would_block) => continue,
+                }
+            }
+        }
+    }
+
+    impl AsyncWrite for AsyncPty {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            let this = self.get_mut();
+
+            loop {
+                let mut guard = match this.inner.poll_write_ready(cx) {
+                    Poll::Ready(guard) => guard?,
+                    Poll::Pending => return Poll::Pending,
+                };
+
+                let result = guard.try_io(|inner| {
+                    nix::unistd::write(inner.get_ref().0, buf).map_err(io::Error::from)
+                });
+
+                match result {
+                    Ok(result) => return Poll::Ready(result),
+                    Err(_would_block) => continue,
+                }
+            }
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    // SIGWINCH is process-directed and its signal mask is per-thread, so
+    // blocking it here only protects the calling thread: on a multi-threaded
+    // runtime the kernel can just as well hand the signal to a worker thread
+    // that never blocked it, where the default (ignore) disposition drops it
+    // before it ever reaches the signalfd. Require a current-thread runtime
+    // so the one thread that blocks SIGWINCH is the only thread there is.
+    pub async fn exec_async<S: AsRef<str>, R: AsyncRecorder>(
+        args: &[S],
+        recorder: &mut R,
+    ) -> anyhow::Result<i32> {
+        let flavor = tokio::runtime::Handle::current().runtime_flavor();
+
+        if flavor != tokio::runtime::RuntimeFlavor::CurrentThread {
+            anyhow::bail!(
+                "exec_async requires a current-thread tokio runtime \
+                 (SIGWINCH blocking is per-thread)"
+            );
+        }
+
+        let tty = open_tty()?;
+        let winsize = get_tty_size(tty.as_raw_fd());
+        recorder.start((winsize.ws_col, winsize.ws_row)).await?;
+
+        let mut sigwinch_mask = signal::SigSet::empty();
+        sigwinch_mask.add(signal::Signal::SIGWINCH);
+        sigwinch_mask.thread_block()?;
+        let signal_fd =
+            signalfd::SignalFd::with_flags(&sigwinch_mask, signalfd::SfdFlags::SFD_NONBLOCK)?;
+
+        let result = unsafe { pty::forkpty(Some(&winsize), None) }?;
+
+        match result.fork_result {
+            ForkResult::Parent { child } => {
+                handle_parent_async(result.master, tty, child, winsize, signal_fd, recorder).await
+            }
+
+            ForkResult::Child => {
+                handle_child(args)?;
+                unreachable!();
+            }
+        }
+    }
+
+    async fn handle_parent_async<R: AsyncRecorder>(
+        master: OwnedFd,
+        tty: fs::File,
+        child: unistd::Pid,
+        winsize: pty::Winsize,
+        signal_fd: signalfd::SignalFd,
+        recorder: &mut R,
+    ) -> anyhow::Result<i32> {
+        let copy_result = copy_async(master, tty, winsize, signal_fd, recorder).await;
+        let wait_result = tokio::task::spawn_blocking(move || wait::waitpid(child, None)).await?;
+        copy_result?;
+
+        match wait_result {
+            Ok(wait::WaitStatus::Exited(_pid, status)) => Ok(status),
+            Ok(wait::WaitStatus::Signaled(_pid, signal, ..)) => Ok(128 + signal as i32),
+            Ok(_) => Ok(1),
+            Err(e) => Err(anyhow::anyhow!(e)),
+        }
+    }
+
+    async fn copy_async<R: AsyncRecorder>(
+        master: OwnedFd,
+        tty: fs::File,
+        mut last_size: pty::Winsize,
+        signal_fd: signalfd::SignalFd,
+        recorder: &mut R,
+    ) -> anyhow::Result<()> {
+        let master_fd = master.as_raw_fd();
+        let (mut master_reader, mut master_writer) = tokio::io::split(AsyncPty::new(master_fd)?);
+
+        let mut tty = tty.into_raw_mode()?;
+        let tty_fd = tty.as_raw_fd();
+        let (mut tty_reader, mut tty_writer) = tokio::io::split(AsyncPty::new(tty_fd)?);
+
+        let signal_raw_fd = signal_fd.as_raw_fd();
+        let signal_io = AsyncFd::new(PtyIo(signal_raw_fd))?;
+
+        let mut master_buf = [0u8; BUF_SIZE];
+        let mut tty_buf = [0u8; BUF_SIZE];
+        let mut output: Vec<u8> = Vec::with_capacity(BUF_SIZE);
+        let mut input: Vec<u8> = Vec::with_capacity(BUF_SIZE);
+        let mut master_closed = false;
+
+        loop {
+            tokio::select! {
+                result = master_reader.read(&mut master_buf), if !master_closed => {
+                    let n = result?;
+
+                    if n == 0 {
+                        master_closed = true;
+
+                        if output.is_empty() {
+                            return Ok(());
+                        }
+                    } else {
+                        recorder.output(&master_buf[..n]).await;
+                        output.extend_from_slice(&master_buf[..n]);
+                    }
+                }
+
+                result = tty_reader.read(&mut tty_buf) => {
+                    let n = result?;
+
+                    if n == 0 {
+                        return Ok(());
+                    }
+
+                    recorder.input(&tty_buf[..n]).await;
+                    input.extend_from_slice(&tty_buf[..n]);
+                }
+
+                result = tty_writer.write(&output), if !output.is_empty() => {
+                    let n = result?;
+                    output.drain(..n);
+
+                    if master_closed && output.is_empty() {
+                        return Ok(());
+                    }
+                }
+
+                result = master_writer.write(&input), if !input.is_empty() => {
+                    let n = result?;
+                    input.drain(..n);
+                }
+
+                result = signal_io.readable() => {
+                    let mut guard = result?;
+                    guard.clear_ready();
+
+                    while signal_fd.read_signal()?.is_some() {}
+
+                    let winsize = get_tty_size(tty_fd);
+
+                    if (winsize.ws_col, winsize.ws_row) != (last_size.ws_col, last_size.ws_row) {
+                        last_size = winsize;
+                        unsafe { libc::ioctl(master_fd, libc::TIOCSWINSZ, &winsize) };
+                        recorder.resize((winsize.ws_col, winsize.ws_row)).await;
+                    }
+                }
+            }
+        }
+    }
+}